@@ -0,0 +1,425 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020-2021 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The `benchmark` subcommand and its two execution paths: the `v1` path that drives a runtime
+//! WASM blob against genesis storage taken from a chain spec, and the `omni` path that builds
+//! genesis entirely through the runtime's own `GenesisBuilder` API. See [`crate::omni_bencher`]
+//! for the latter.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use codec::{Decode, Encode};
+use sc_executor::{WasmExecutionMethod, WasmExecutor};
+use serde::Deserialize;
+use sp_core::traits::{CallContext, CodeExecutor, RuntimeCode};
+use sp_state_machine::BasicExternalities;
+use structopt::StructOpt;
+
+use frame_benchmarking::{AnalysisChoice, BenchmarkBatchSplitResults};
+use frame_support::traits::StorageInfo;
+
+use crate::{compare, omni_bencher::OmniBenchmarkCmd, storage::StorageCmd, utils};
+
+/// Merge the `BenchmarkBatchSplitResults` of repeated `--external-repeat` invocations, one
+/// `Vec` per repeat, concatenating the samples of batches that share a pallet/instance/benchmark.
+///
+/// Used by both the `omni` and `v1` paths, since both repeat the same way from the client side.
+pub(crate) fn merge_batches(
+	repeats: Vec<Vec<BenchmarkBatchSplitResults>>,
+) -> Vec<BenchmarkBatchSplitResults> {
+	let mut merged: Vec<BenchmarkBatchSplitResults> = Vec::new();
+	for batches in repeats {
+		for batch in batches {
+			match merged.iter_mut().find(|existing| {
+				existing.pallet == batch.pallet &&
+					existing.instance == batch.instance &&
+					existing.benchmark == batch.benchmark
+			}) {
+				Some(existing) => {
+					existing.time_results.extend(batch.time_results);
+					existing.db_results.extend(batch.db_results);
+				},
+				None => merged.push(batch),
+			}
+		}
+	}
+	merged
+}
+
+/// Parameters that select which extrinsics to run and how many samples to take. Shared between
+/// the `v1` node-backed path and the `omni` runtime-only path.
+#[derive(Debug, StructOpt, Clone)]
+pub struct BenchmarkParams {
+	/// Select a FRAME Pallet to benchmark, or `*` for all (in which case `extrinsic` must also
+	/// be `*`).
+	#[structopt(short, long)]
+	pub pallet: String,
+
+	/// Select an extrinsic to benchmark, or `*` for all.
+	#[structopt(short, long)]
+	pub extrinsic: String,
+
+	/// Select how many samples we should take across the variable components.
+	#[structopt(short, long, default_value = "1")]
+	pub steps: Vec<u32>,
+
+	/// Indicates lowest values for each of the component ranges.
+	#[structopt(long = "low", use_delimiter = true)]
+	pub lowest_range_values: Vec<u32>,
+
+	/// Indicates highest values for each of the component ranges.
+	#[structopt(long = "high", use_delimiter = true)]
+	pub highest_range_values: Vec<u32>,
+
+	/// Select how many repetitions of this benchmark should run from within the wasm.
+	#[structopt(short, long, default_value = "1")]
+	pub repeat: u32,
+
+	/// Select how many repetitions of this benchmark should run from the client.
+	#[structopt(long, default_value = "1")]
+	pub external_repeat: u32,
+}
+
+/// Parameters controlling how the measured samples are written out. Shared between the `v1` and
+/// `omni` paths so that both feed the same [`crate::writer`] / [`crate::html_writer`] pipeline.
+#[derive(Debug, StructOpt, Clone)]
+pub struct BenchmarkOutputParams {
+	/// Output the benchmarks to a Rust file at the given path.
+	#[structopt(long)]
+	pub output: Option<PathBuf>,
+
+	/// Path to Handlebars template file used for outputting benchmark results. (Optional)
+	#[structopt(long)]
+	pub template: Option<PathBuf>,
+
+	/// Header file to insert while generating the weight file.
+	#[structopt(long)]
+	pub header: Option<PathBuf>,
+
+	/// Don't print the median-slopes linear regression analysis.
+	#[structopt(long)]
+	pub no_median_slopes: bool,
+
+	/// Don't print the min-squares linear regression analysis.
+	#[structopt(long)]
+	pub no_min_squares: bool,
+
+	/// Write the per-pallet benchmark data as JSON to the given file, for machine consumption.
+	#[structopt(long)]
+	pub json_file: Option<PathBuf>,
+
+	/// Write the per-pallet benchmark data as flattened CSV to the given file.
+	#[structopt(long)]
+	pub csv_file: Option<PathBuf>,
+
+	/// Compare the new results against a baseline previously written with `--json-file`, and
+	/// report the per-benchmark percentage change.
+	#[structopt(long)]
+	pub compare_with: Option<PathBuf>,
+
+	/// When used together with `--compare-with`, fail the command if any metric regresses by
+	/// more than this percentage, e.g. `10` for 10%.
+	#[structopt(long)]
+	pub fail_on_regression: Option<f32>,
+}
+
+impl BenchmarkOutputParams {
+	/// Which analysis function should be used when outputting benchmarks.
+	pub fn analysis_choice(&self) -> Result<AnalysisChoice, std::io::Error> {
+		match (self.no_median_slopes, self.no_min_squares) {
+			(false, false) => Ok(AnalysisChoice::default()),
+			(true, false) => Ok(AnalysisChoice::MinSquares),
+			(false, true) => Ok(AnalysisChoice::MedianSlopes),
+			(true, true) =>
+				Err(crate::utils::io_error("Cannot disable both analysis functions.")),
+		}
+	}
+}
+
+/// Benchmark the extrinsics of a FRAME runtime.
+///
+/// A subcommand is required: pass `omni` to benchmark a standalone runtime WASM blob without a
+/// node or chain spec, `v1` to benchmark a runtime WASM blob against the genesis storage of a raw
+/// chain spec, or `storage` to benchmark a node's database.
+#[derive(Debug, StructOpt)]
+pub enum BenchmarkCmd {
+	/// Benchmark a runtime WASM blob directly, with no node or chain spec required. This is the
+	/// default way to benchmark a runtime artifact, e.g. in CI.
+	Omni(OmniBenchmarkCmd),
+
+	/// The legacy benchmarking path: run the benchmark extrinsics of a runtime WASM blob in
+	/// memory against the genesis storage of a raw chain spec. No node is started or connected
+	/// to.
+	#[structopt(name = "v1")]
+	V1(PalletCmd),
+
+	/// Benchmark the per-key read/write latency of a node's database, to calibrate the storage
+	/// weight constants rather than the extrinsic ones.
+	Storage(StorageCmd),
+}
+
+/// The original, node-backed benchmarking path. This is what used to be the whole of
+/// `BenchmarkCmd` before the omni-bencher was added; it is now reachable as `benchmark v1`.
+#[derive(Debug, StructOpt)]
+pub struct PalletCmd {
+	/// Path to a built runtime WASM blob to execute the benchmark extrinsics against.
+	#[structopt(long)]
+	pub runtime: PathBuf,
+
+	/// Path to a raw chain spec JSON file. Its `genesis.raw.top` storage map seeds genesis,
+	/// in place of the `GenesisBuilder` API the `omni` path uses.
+	#[structopt(long)]
+	pub chain: PathBuf,
+
+	#[structopt(flatten)]
+	pub params: BenchmarkParams,
+
+	#[structopt(flatten)]
+	pub output: BenchmarkOutputParams,
+}
+
+/// The slice of the raw chain spec JSON format needed to recover genesis storage.
+#[derive(Deserialize)]
+struct RawChainSpec {
+	genesis: RawGenesis,
+}
+
+#[derive(Deserialize)]
+struct RawGenesis {
+	raw: RawGenesisStorage,
+}
+
+#[derive(Deserialize)]
+struct RawGenesisStorage {
+	top: HashMap<String, String>,
+}
+
+impl PalletCmd {
+	/// Run the benchmark extrinsics against `self.runtime`, seeding genesis storage from the
+	/// chain spec at `self.chain` rather than the runtime's own `GenesisBuilder` API (compare
+	/// [`crate::omni_bencher::OmniBenchmarkCmd::run`], which needs no chain spec at all).
+	///
+	/// Repeats the whole call `self.params.external_repeat` times, merging the samples of each
+	/// repeat into the matching batch.
+	pub fn run(&self) -> sc_cli::Result<Vec<BenchmarkBatchSplitResults>> {
+		let repeats = (0..self.params.external_repeat.max(1))
+			.map(|_| self.dispatch_once())
+			.collect::<sc_cli::Result<Vec<_>>>()?;
+		Ok(merge_batches(repeats))
+	}
+
+	fn dispatch_once(&self) -> sc_cli::Result<Vec<BenchmarkBatchSplitResults>> {
+		let code = std::fs::read(&self.runtime)
+			.map_err(|e| sc_cli::Error::Input(format!("could not read runtime blob: {}", e)))?;
+		let code_hash = sp_core::blake2_256(&code).to_vec();
+		let runtime_code =
+			RuntimeCode { code_fetcher: &code[..], hash: code_hash, heap_pages: None };
+
+		let executor = WasmExecutor::<sp_io::SubstrateHostFunctions>::builder()
+			.with_execution_method(WasmExecutionMethod::default())
+			.build();
+
+		let mut ext = BasicExternalities::new(self.genesis_storage()?);
+
+		let call_params = (
+			self.params.pallet.as_bytes().to_vec(),
+			self.params.extrinsic.as_bytes().to_vec(),
+			self.params.lowest_range_values.clone(),
+			self.params.highest_range_values.clone(),
+			self.params.steps.clone(),
+			self.params.repeat,
+			true, // whitelist is taken from the runtime's own defaults.
+		)
+			.encode();
+
+		let raw_results = executor
+			.call(
+				&mut ext.ext(),
+				&runtime_code,
+				"Benchmark_dispatch_benchmark",
+				&call_params,
+				CallContext::Offchain,
+			)
+			.0
+			.map_err(|e| sc_cli::Error::Application(e.into()))?;
+
+		let results: Result<Vec<BenchmarkBatchSplitResults>, String> =
+			Decode::decode(&mut &raw_results[..])
+				.map_err(|e| format!("failed to decode benchmark results: {:?}", e))?;
+
+		results.map_err(|e| sc_cli::Error::Application(e.into()))
+	}
+
+	/// Parse `self.chain` into the genesis [`sp_core::storage::Storage`] the benchmark extrinsics
+	/// run against. Only the raw `top` trie is read; child tries aren't needed for benchmarking.
+	fn genesis_storage(&self) -> sc_cli::Result<sp_core::storage::Storage> {
+		let raw = std::fs::read_to_string(&self.chain)
+			.map_err(|e| sc_cli::Error::Input(format!("could not read chain spec: {}", e)))?;
+		let spec: RawChainSpec = serde_json::from_str(&raw)
+			.map_err(|e| sc_cli::Error::Input(format!("could not parse chain spec: {}", e)))?;
+
+		let top = spec
+			.genesis
+			.raw
+			.top
+			.into_iter()
+			.map(|(key, value)| {
+				let key = sp_core::bytes::from_hex(&key)
+					.map_err(|e| sc_cli::Error::Input(format!("invalid chain spec key: {}", e)))?;
+				let value = sp_core::bytes::from_hex(&value).map_err(|e| {
+					sc_cli::Error::Input(format!("invalid chain spec value: {}", e))
+				})?;
+				Ok((key, value))
+			})
+			.collect::<sc_cli::Result<_>>()?;
+
+		Ok(sp_core::storage::Storage { top, children_default: Default::default() })
+	}
+}
+
+impl BenchmarkCmd {
+	/// Run whichever path was selected and route the resulting samples through the shared
+	/// `writer` / `html_writer` / `json_writer` pipeline.
+	///
+	/// Returns an error if `--fail-on-regression` was given and a metric regressed beyond the
+	/// threshold, so a CI caller sees a non-zero exit code. This check does not depend on
+	/// `--output` being given: a pipeline that only wants `--json-file` plus gating must still
+	/// get it.
+	pub fn run(&self) -> sc_cli::Result<()> {
+		let batches = match self {
+			BenchmarkCmd::Omni(cmd) => cmd.run()?,
+			BenchmarkCmd::V1(cmd) => cmd.run()?,
+			BenchmarkCmd::Storage(cmd) => cmd.run()?,
+		};
+		// None of the three paths runs against a live client, so there is no `StorageInfo` to
+		// report here; the weight file and HTML report simply omit that section.
+		let storage_info: Vec<StorageInfo> = Vec::new();
+
+		let analysis_choice = self.analysis_choice()?;
+		let all_results = utils::map_results(&batches, &storage_info, &analysis_choice)
+			.map_err(|e| sc_cli::Error::Input(e.to_string()))?;
+
+		// Diff against a baseline unconditionally, so `--fail-on-regression` gates CI regardless
+		// of whether a weight file/HTML report was also requested via `--output`.
+		let comparison = match self.compare_with() {
+			Some(baseline_path) => {
+				let baseline = compare::load_baseline(baseline_path)
+					.map_err(|e| sc_cli::Error::Input(e.to_string()))?;
+				compare::compare(&all_results, &baseline)
+			},
+			None => Vec::new(),
+		};
+		if !comparison.is_empty() {
+			print!("{}", compare::render_text_table(&comparison));
+		}
+		let has_regression = self
+			.fail_on_regression()
+			.map(|threshold| compare::has_regression(&comparison, threshold))
+			.unwrap_or(false);
+
+		if let Some(path) = self.output_path() {
+			crate::writer::write_results(&batches, &storage_info, path, self)
+				.map_err(|e| sc_cli::Error::Input(e.to_string()))?;
+			// Given a file rather than a directory, put the HTML report beside the weight file
+			// instead of overwriting it.
+			let html_path = if path.is_dir() { path.clone() } else { path.with_extension("html") };
+			crate::html_writer::write_results(&batches, &storage_info, &html_path, self, &comparison)
+				.map_err(|e| sc_cli::Error::Input(e.to_string()))?;
+		}
+		crate::json_writer::write_results(&batches, &storage_info, self)
+			.map_err(|e| sc_cli::Error::Input(e.to_string()))?;
+
+		if has_regression {
+			return Err(sc_cli::Error::Input(
+				"benchmark regressed beyond the --fail-on-regression threshold".to_string(),
+			))
+		}
+		Ok(())
+	}
+
+	/// Path to write the Rust weight file / HTML report into, if requested.
+	pub fn output_path(&self) -> &Option<PathBuf> {
+		match self {
+			BenchmarkCmd::Omni(cmd) => &cmd.output.output,
+			BenchmarkCmd::V1(cmd) => &cmd.output.output,
+			BenchmarkCmd::Storage(cmd) => &cmd.output.output,
+		}
+	}
+
+	/// Path to read a custom Handlebars template from, if one was given.
+	pub fn template(&self) -> &Option<PathBuf> {
+		match self {
+			BenchmarkCmd::Omni(cmd) => &cmd.output.template,
+			BenchmarkCmd::V1(cmd) => &cmd.output.template,
+			BenchmarkCmd::Storage(cmd) => &cmd.output.template,
+		}
+	}
+
+	/// Path to a header file to prepend to the generated weight file, if any.
+	pub fn header(&self) -> &Option<PathBuf> {
+		match self {
+			BenchmarkCmd::Omni(cmd) => &cmd.output.header,
+			BenchmarkCmd::V1(cmd) => &cmd.output.header,
+			BenchmarkCmd::Storage(cmd) => &cmd.output.header,
+		}
+	}
+
+	/// Which analysis function should be used when outputting benchmarks.
+	pub fn analysis_choice(&self) -> Result<AnalysisChoice, std::io::Error> {
+		match self {
+			BenchmarkCmd::Omni(cmd) => cmd.output.analysis_choice(),
+			BenchmarkCmd::V1(cmd) => cmd.output.analysis_choice(),
+			BenchmarkCmd::Storage(cmd) => cmd.output.analysis_choice(),
+		}
+	}
+
+	/// Path to write the machine-readable JSON summary to, if requested.
+	pub fn json_file(&self) -> &Option<PathBuf> {
+		match self {
+			BenchmarkCmd::Omni(cmd) => &cmd.output.json_file,
+			BenchmarkCmd::V1(cmd) => &cmd.output.json_file,
+			BenchmarkCmd::Storage(cmd) => &cmd.output.json_file,
+		}
+	}
+
+	/// Path to write the flattened CSV summary to, if requested.
+	pub fn csv_file(&self) -> &Option<PathBuf> {
+		match self {
+			BenchmarkCmd::Omni(cmd) => &cmd.output.csv_file,
+			BenchmarkCmd::V1(cmd) => &cmd.output.csv_file,
+			BenchmarkCmd::Storage(cmd) => &cmd.output.csv_file,
+		}
+	}
+
+	/// Baseline JSON file to diff the new results against, if requested.
+	pub fn compare_with(&self) -> &Option<PathBuf> {
+		match self {
+			BenchmarkCmd::Omni(cmd) => &cmd.output.compare_with,
+			BenchmarkCmd::V1(cmd) => &cmd.output.compare_with,
+			BenchmarkCmd::Storage(cmd) => &cmd.output.compare_with,
+		}
+	}
+
+	/// Regression threshold (in percent) beyond which the command should exit non-zero.
+	pub fn fail_on_regression(&self) -> Option<f32> {
+		match self {
+			BenchmarkCmd::Omni(cmd) => cmd.output.fail_on_regression,
+			BenchmarkCmd::V1(cmd) => cmd.output.fail_on_regression,
+			BenchmarkCmd::Storage(cmd) => cmd.output.fail_on_regression,
+		}
+	}
+}
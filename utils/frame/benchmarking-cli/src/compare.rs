@@ -0,0 +1,197 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020-2021 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Compares freshly mapped benchmark results against a baseline, so CI can flag weight
+// regressions instead of relying on someone eyeballing the generated files.
+
+use std::{collections::HashMap, fs};
+
+use serde::Serialize;
+
+use crate::utils::{self, BenchmarkData};
+
+/// A single metric (base weight, a component slope, a read/write count, ...) before and after.
+#[derive(Serialize, Debug, Clone)]
+pub struct MetricChange {
+	pub pallet: String,
+	pub instance: String,
+	pub benchmark: String,
+	pub metric: String,
+	pub baseline: u128,
+	pub current: u128,
+	/// Positive means the metric got worse (larger), negative means it improved.
+	pub percent_change: f32,
+}
+
+/// Load a baseline previously written with `--json-file` (see `json_writer`).
+pub fn load_baseline(
+	path: &std::path::Path,
+) -> Result<HashMap<String, Vec<BenchmarkData>>, std::io::Error> {
+	let file = fs::File::open(path)?;
+	serde_json::from_reader(file).map_err(|e| utils::io_error(&e.to_string()))
+}
+
+fn percent_change(baseline: u128, current: u128) -> f32 {
+	if baseline == 0 {
+		return if current == 0 { 0.0 } else { 100.0 }
+	}
+	((current as f64 - baseline as f64) / baseline as f64 * 100.0) as f32
+}
+
+/// Diff `current` against `baseline`, one [`MetricChange`] per metric that exists in both.
+///
+/// The metrics compared are the base weight, the base reads, the base writes and the weight
+/// slope of each component; proof sizes and the per-component read/write slopes are not compared.
+///
+/// Benchmarks that only exist in one of the two sides (new/removed extrinsics) are skipped:
+/// there is nothing to regress against.
+pub fn compare(
+	current: &HashMap<(String, String), Vec<BenchmarkData>>,
+	baseline: &HashMap<String, Vec<BenchmarkData>>,
+) -> Vec<MetricChange> {
+	let mut changes = Vec::new();
+
+	for ((pallet, instance), benchmarks) in current.iter() {
+		let key = format!("{}::{}", pallet, instance);
+		let baseline_benchmarks = match baseline.get(&key) {
+			Some(b) => b,
+			None => continue,
+		};
+
+		for benchmark in benchmarks {
+			let baseline_benchmark =
+				match baseline_benchmarks.iter().find(|b| b.name == benchmark.name) {
+					Some(b) => b,
+					None => continue,
+				};
+
+			let mut push = |metric: &str, baseline: u128, current: u128| {
+				changes.push(MetricChange {
+					pallet: pallet.clone(),
+					instance: instance.clone(),
+					benchmark: benchmark.name.clone(),
+					metric: metric.to_string(),
+					baseline,
+					current,
+					percent_change: percent_change(baseline, current),
+				});
+			};
+
+			push("base_weight", baseline_benchmark.base_weight, benchmark.base_weight);
+			push("base_reads", baseline_benchmark.base_reads, benchmark.base_reads);
+			push("base_writes", baseline_benchmark.base_writes, benchmark.base_writes);
+
+			for slope in &benchmark.component_weight {
+				if let Some(baseline_slope) =
+					baseline_benchmark.component_weight.iter().find(|s| s.name == slope.name)
+				{
+					push(&format!("{}_slope", slope.name), baseline_slope.slope, slope.slope);
+				}
+			}
+		}
+	}
+
+	changes
+}
+
+/// Render a short plain-text table, one row per [`MetricChange`] that regressed at all.
+pub fn render_text_table(changes: &[MetricChange]) -> String {
+	let mut out = String::from("pallet::instance::benchmark  metric  baseline  current  change\n");
+	for change in changes.iter().filter(|c| c.percent_change > 0.0) {
+		out.push_str(&format!(
+			"{}::{}::{}  {}  {}  {}  {:+.2}%\n",
+			change.pallet,
+			change.instance,
+			change.benchmark,
+			change.metric,
+			change.baseline,
+			change.current,
+			change.percent_change,
+		));
+	}
+	out
+}
+
+/// Whether any metric regressed beyond `threshold_percent`.
+pub fn has_regression(changes: &[MetricChange], threshold_percent: f32) -> bool {
+	changes.iter().any(|c| c.percent_change > threshold_percent)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::utils::{Component, ComponentSlope};
+
+	fn bench(name: &str, base_weight: u128, slope: u128) -> BenchmarkData {
+		BenchmarkData {
+			name: name.to_string(),
+			components: vec![Component { name: "x".to_string(), is_used: true }],
+			base_weight,
+			component_weight: vec![ComponentSlope { name: "x".to_string(), slope, error: 0 }],
+			base_reads: 0,
+			component_reads: vec![],
+			base_writes: 0,
+			component_writes: vec![],
+			base_proof_size: 0,
+			component_proof_size: vec![],
+		}
+	}
+
+	#[test]
+	fn percent_change_handles_zero_baseline() {
+		assert_eq!(percent_change(0, 0), 0.0);
+		assert_eq!(percent_change(0, 50), 100.0);
+	}
+
+	#[test]
+	fn percent_change_reports_regressions_and_improvements() {
+		assert_eq!(percent_change(100, 120), 20.0);
+		assert_eq!(percent_change(100, 80), -20.0);
+	}
+
+	#[test]
+	fn compare_flags_a_regressed_metric() {
+		let mut current = HashMap::new();
+		current.insert(
+			("pallet".to_string(), "instance".to_string()),
+			vec![bench("bench", 120, 3)],
+		);
+		let mut baseline = HashMap::new();
+		baseline.insert("pallet::instance".to_string(), vec![bench("bench", 100, 2)]);
+
+		let changes = compare(&current, &baseline);
+
+		let base_weight_change = changes.iter().find(|c| c.metric == "base_weight").unwrap();
+		assert_eq!(base_weight_change.baseline, 100);
+		assert_eq!(base_weight_change.current, 120);
+		assert_eq!(base_weight_change.percent_change, 20.0);
+
+		// base_weight regressed by 20% and the `x` slope (2 -> 3) by 50%.
+		assert!(has_regression(&changes, 10.0));
+		assert!(has_regression(&changes, 25.0));
+		assert!(!has_regression(&changes, 50.0));
+	}
+
+	#[test]
+	fn compare_skips_benchmarks_missing_from_the_baseline() {
+		let mut current = HashMap::new();
+		current.insert(("pallet".to_string(), "instance".to_string()), vec![bench("new", 100, 1)]);
+		let baseline = HashMap::new();
+
+		assert!(compare(&current, &baseline).is_empty());
+	}
+}
@@ -0,0 +1,265 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020-2021 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// `write_results` previously committed to whichever single `AnalysisChoice` was selected and had
+// no way to tell a clean fit from a noisy one. This computes R² and per-slope standard errors for
+// every candidate model against the same samples, so that judgment can be made visible instead of
+// assumed.
+
+use frame_benchmarking::{Analysis, AnalysisChoice, BenchmarkResult, BenchmarkSelector};
+use serde::Serialize;
+
+/// Goodness-of-fit for one [`AnalysisChoice`] against one set of measured samples.
+#[derive(Serialize, Debug, Clone)]
+pub struct ModelFit {
+	/// Human readable name of the analysis strategy, e.g. "Min Squares".
+	pub name: String,
+	pub base: u128,
+	/// Per-component slope; its standard error is at the same index of `slope_std_errors`.
+	pub slopes: Vec<(String, u128)>,
+	pub slope_std_errors: Vec<(String, f64)>,
+	/// Coefficient of determination, in `[0, 1]` for a sane fit (can go negative for a
+	/// pathologically bad one).
+	pub r_squared: f64,
+}
+
+/// Run every [`AnalysisChoice`] against `data` and report how well each one fits.
+///
+/// `component_names` must list every distinct component in the order the data's
+/// `BenchmarkResult::components` vectors use it, so the design matrix columns line up with the
+/// slopes `Analysis` returns.
+pub fn fit_all_models(
+	data: &[BenchmarkResult],
+	selector: BenchmarkSelector,
+	component_names: &[String],
+) -> Vec<ModelFit> {
+	let choices = [
+		(AnalysisChoice::MinSquares, "Min Squares"),
+		(AnalysisChoice::MedianSlopes, "Median Slopes"),
+		(AnalysisChoice::Max, "Max"),
+	];
+
+	choices
+		.iter()
+		.filter_map(|(choice, name)| {
+			let analysis = match choice {
+				AnalysisChoice::MinSquares => Analysis::min_squares_iqr(data, selector),
+				AnalysisChoice::MedianSlopes => Analysis::median_slopes(data, selector),
+				AnalysisChoice::Max => Analysis::max(data, selector),
+			}?;
+
+			Some(build_fit(name.to_string(), &analysis, data, component_names))
+		})
+		.collect()
+}
+
+fn build_fit(
+	name: String,
+	analysis: &Analysis,
+	data: &[BenchmarkResult],
+	component_names: &[String],
+) -> ModelFit {
+	let base = analysis.base;
+	let slopes: Vec<u128> = analysis.slopes.clone();
+
+	// y_j and predicted ŷ_j for every sample, plus the design matrix row [x_1, .., x_k] per
+	// sample, used below for both R² and the slope standard errors.
+	let mut y = Vec::with_capacity(data.len());
+	let mut y_hat = Vec::with_capacity(data.len());
+	let mut design = Vec::with_capacity(data.len());
+
+	for result in data {
+		let x: Vec<f64> = component_names
+			.iter()
+			.map(|name| {
+				result
+					.components
+					.iter()
+					.find(|(p, _)| &p.to_string() == name)
+					.map(|(_, v)| *v as f64)
+					.unwrap_or(0.0)
+			})
+			.collect();
+
+		let predicted = base as f64 +
+			x.iter().zip(slopes.iter()).map(|(xi, s)| xi * *s as f64).sum::<f64>();
+
+		y.push(result.extrinsic_time as f64);
+		y_hat.push(predicted);
+		design.push(x);
+	}
+
+	let mean_y = y.iter().sum::<f64>() / y.len().max(1) as f64;
+	let rss: f64 = y.iter().zip(y_hat.iter()).map(|(yj, yhj)| (yj - yhj).powi(2)).sum();
+	let tss: f64 = y.iter().map(|yj| (yj - mean_y).powi(2)).sum();
+	let r_squared = if tss > 0.0 { 1.0 - rss / tss } else { 1.0 };
+
+	let n = data.len();
+	let k = component_names.len();
+	let slope_std_errors = if n > k && k > 0 {
+		match invert(&gram_matrix(&design)) {
+			Some(inv) => {
+				let sigma_sq = rss / (n - k) as f64;
+				component_names
+					.iter()
+					.enumerate()
+					.map(|(i, name)| (name.clone(), (sigma_sq * inv[i][i]).max(0.0).sqrt()))
+					.collect()
+			},
+			None => component_names.iter().map(|name| (name.clone(), 0.0)).collect(),
+		}
+	} else {
+		component_names.iter().map(|name| (name.clone(), 0.0)).collect()
+	};
+
+	ModelFit {
+		name,
+		base,
+		slopes: component_names.iter().cloned().zip(slopes.into_iter()).collect(),
+		slope_std_errors,
+		r_squared,
+	}
+}
+
+/// `X^T X`, the Gram matrix of the design matrix (one row per sample, one column per component).
+fn gram_matrix(design: &[Vec<f64>]) -> Vec<Vec<f64>> {
+	let k = design.first().map(|r| r.len()).unwrap_or(0);
+	let mut gram = vec![vec![0.0; k]; k];
+	for row in design {
+		for i in 0..k {
+			for j in 0..k {
+				gram[i][j] += row[i] * row[j];
+			}
+		}
+	}
+	gram
+}
+
+/// Gauss-Jordan matrix inversion. `k` (the component count) is small in practice, so this is
+/// simpler than pulling in a linear algebra crate for it.
+fn invert(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+	let k = matrix.len();
+	if k == 0 {
+		return Some(Vec::new())
+	}
+	let mut aug: Vec<Vec<f64>> = matrix
+		.iter()
+		.enumerate()
+		.map(|(i, row)| {
+			let mut row = row.clone();
+			row.extend((0..k).map(|j| if i == j { 1.0 } else { 0.0 }));
+			row
+		})
+		.collect();
+
+	for col in 0..k {
+		let pivot_row = (col..k).max_by(|&a, &b| aug[a][col].abs().total_cmp(&aug[b][col].abs()))?;
+		if aug[pivot_row][col].abs() < 1e-12 {
+			return None
+		}
+		aug.swap(col, pivot_row);
+
+		let pivot = aug[col][col];
+		for value in aug[col].iter_mut() {
+			*value /= pivot;
+		}
+
+		for row in 0..k {
+			if row == col {
+				continue
+			}
+			let factor = aug[row][col];
+			for c in 0..(2 * k) {
+				aug[row][c] -= factor * aug[col][c];
+			}
+		}
+	}
+
+	Some(aug.into_iter().map(|row| row[k..].to_vec()).collect())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use frame_benchmarking::BenchmarkParameter;
+
+	#[test]
+	fn gram_matrix_is_x_transpose_x() {
+		let design = vec![vec![1.0, 2.0], vec![3.0, 4.0]];
+		// [1 3] [1 2]   [10 14]
+		// [2 4] [3 4] = [14 20]
+		assert_eq!(gram_matrix(&design), vec![vec![10.0, 14.0], vec![14.0, 20.0]]);
+	}
+
+	#[test]
+	fn invert_round_trips_through_identity() {
+		let matrix = vec![vec![4.0, 7.0], vec![2.0, 6.0]];
+		let inverse = invert(&matrix).unwrap();
+
+		// matrix * inverse should be (approximately) the identity.
+		for i in 0..2 {
+			for j in 0..2 {
+				let dot: f64 = (0..2).map(|k| matrix[i][k] * inverse[k][j]).sum();
+				let expected = if i == j { 1.0 } else { 0.0 };
+				assert!((dot - expected).abs() < 1e-9);
+			}
+		}
+	}
+
+	#[test]
+	fn invert_rejects_a_singular_matrix() {
+		assert!(invert(&[vec![1.0, 2.0], vec![2.0, 4.0]]).is_none());
+	}
+
+	fn point(x: u32, y: u128) -> BenchmarkResult {
+		BenchmarkResult {
+			components: vec![(BenchmarkParameter::a, x)],
+			extrinsic_time: y,
+			storage_root_time: y,
+			reads: 0,
+			repeat_reads: 0,
+			writes: 0,
+			repeat_writes: 0,
+			proof_size: 0,
+			keys: vec![],
+		}
+	}
+
+	#[test]
+	fn build_fit_reports_a_perfect_fit_for_points_on_a_line() {
+		// y = 10 + 3x exactly, so whichever model recovers it should fit perfectly.
+		let data: Vec<BenchmarkResult> = (0..5).map(|x| point(x, 10 + 3 * x as u128)).collect();
+		let analysis = Analysis::min_squares_iqr(&data, BenchmarkSelector::ExtrinsicTime).unwrap();
+
+		let fit = build_fit("Min Squares".to_string(), &analysis, &data, &["a".to_string()]);
+
+		assert!((fit.r_squared - 1.0).abs() < 1e-9);
+	}
+
+	#[test]
+	fn fit_all_models_runs_every_analysis_choice() {
+		let data: Vec<BenchmarkResult> = (0..5).map(|x| point(x, 10 + 3 * x as u128)).collect();
+
+		let fits = fit_all_models(&data, BenchmarkSelector::ExtrinsicTime, &["a".to_string()]);
+
+		assert_eq!(fits.iter().map(|f| f.name.clone()).collect::<Vec<_>>(), vec![
+			"Min Squares".to_string(),
+			"Median Slopes".to_string(),
+			"Max".to_string(),
+		]);
+	}
+}
@@ -17,8 +17,8 @@
 //
 // Outputs benchmark results to a HTML file with details, summaries and charts
 
-use std::{convert::TryInto, fs, io::Read, path::PathBuf};
-use crate::{BenchmarkCmd, utils::{self, CmdData}};
+use std::{convert::TryInto, fs, path::PathBuf};
+use crate::{compare, goodness_of_fit::{self, ModelFit}, BenchmarkCmd, utils::{self, CmdData}};
 use frame_benchmarking::{
 	Analysis, AnalysisChoice, BenchmarkBatchSplitResults, BenchmarkResult, BenchmarkSelector,
 	RegressionModel,
@@ -27,7 +27,7 @@ use frame_support::traits::StorageInfo;
 use serde::Serialize;
 use inflector::Inflector;
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
-//const TEMPLATE: &str = include_str!("./html_template.hbs");
+const TEMPLATE: &str = include_str!("./html_template.hbs");
 
 // This is the final structure we will pass to the Handlebars template.
 #[derive(Serialize, Default, Debug, Clone)]
@@ -39,18 +39,27 @@ struct TemplateData {
 	instance: String,
 	cmd: utils::CmdData,
 	benchmarks: Vec<utils::BenchmarkData>,
+	// Present only when `--compare-with` was given: one row per compared metric, changed or not,
+	// rendered as a table in `html_template.hbs`.
+	comparison: Vec<compare::MetricChange>,
+	// One entry per `benchmarks` entry (same order): how well each `AnalysisChoice` fits that
+	// benchmark's measured samples, rendered as a "Candidate models" table (base, R², per-slope
+	// value and standard error) right under that benchmark's own table in `html_template.hbs`.
+	models: Vec<Vec<ModelFit>>,
 }
 
+/// Write the HTML report(s).
+///
+/// `comparison` is computed once by the caller (`BenchmarkCmd::run`) so that regression gating
+/// doesn't depend on an HTML report being requested at all; this only splits it per pallet/
+/// instance for rendering.
 pub fn write_results(
 	batches: &[BenchmarkBatchSplitResults],
 	storage_info: &[StorageInfo],
 	path: &PathBuf,
 	cmd: &BenchmarkCmd,
+	comparison: &[compare::MetricChange],
 ) -> Result<(), std::io::Error> {
-	let mut file = fs::File::open("./html_template.hbs")?;
-	let mut TEMPLATE = String::new();
-	file.read_to_string(&mut TEMPLATE)?;
-
 	// Date string metadata
 	let date = chrono::Utc::now().format("%Y-%m-%d").to_string();
 
@@ -69,6 +78,28 @@ pub fn write_results(
 
 	// Organize results by pallet into a JSON map
 	let all_results = utils::map_results(batches, &storage_info, &analysis_choice)?;
+
+	// For every mapped benchmark, find the raw samples it came from and fit every
+	// `AnalysisChoice` against them, so the template can render them side by side.
+	let model_fits = |pallet: &str, instance: &str, benchmark: &utils::BenchmarkData| {
+		let batch = batches.iter().find(|b| {
+			b.pallet == pallet.as_bytes() &&
+				b.instance == instance.as_bytes() &&
+				b.benchmark == benchmark.name.as_bytes()
+		});
+		let batch = match batch {
+			Some(b) => b,
+			None => return Vec::new(),
+		};
+		let component_names: Vec<String> =
+			benchmark.components.iter().map(|c| c.name.clone()).collect();
+		goodness_of_fit::fit_all_models(
+			&batch.time_results,
+			BenchmarkSelector::ExtrinsicTime,
+			&component_names,
+		)
+	};
+
 	for ((pallet, instance), results) in all_results.iter() {
 		let mut file_path = path.clone();
 		// If a user only specified a directory...
@@ -92,6 +123,15 @@ pub fn write_results(
 			instance: instance.to_string(),
 			cmd: cmd_data.clone(),
 			benchmarks: results.clone(),
+			comparison: comparison
+				.iter()
+				.filter(|c| &c.pallet == pallet && &c.instance == instance)
+				.cloned()
+				.collect(),
+			// Must stay index-aligned with `benchmarks`: `html_template.hbs` correlates the two by
+			// position (`{{lookup ../models @index}}`) rather than nesting model fits inside each
+			// `BenchmarkData`, so this has to stay a 1:1, same-order `map` over `results`.
+			models: results.iter().map(|b| model_fits(pallet, instance, b)).collect(),
 		};
 
 		let mut output_file = fs::File::create(file_path)?;
@@ -0,0 +1,199 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020-2021 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Outputs benchmark results as machine-readable JSON and/or CSV, both built from the same
+// `utils::BenchmarkData` the Rust and HTML writers already produce. The JSON output keeps that
+// structure nested, keyed by "pallet::instance"; the CSV output flattens it to one row per
+// pallet/benchmark/component, for tooling that wants to load it straight into a table.
+
+use std::{collections::HashMap, fs};
+
+use frame_benchmarking::BenchmarkBatchSplitResults;
+use frame_support::traits::StorageInfo;
+use serde::Serialize;
+
+use crate::{utils, BenchmarkCmd};
+
+/// One row of the flattened CSV output: a single pallet/benchmark/component combination.
+#[derive(Serialize)]
+struct CsvRow<'a> {
+	pallet: &'a str,
+	instance: &'a str,
+	benchmark: &'a str,
+	component: &'a str,
+	base_weight: u128,
+	component_slope: u128,
+	component_error: u128,
+	base_reads: u128,
+	component_reads: u128,
+	base_writes: u128,
+	component_writes: u128,
+	base_proof_size: u128,
+	component_proof_size: u128,
+}
+
+/// Write the mapped benchmark results to `--json-file` and/or `--csv-file`, if requested.
+///
+/// This reuses the same [`utils::map_results`] data that [`crate::writer::write_results`] and
+/// [`crate::html_writer::write_results`] already consume, so the three output formats can never
+/// drift apart.
+pub fn write_results(
+	batches: &[BenchmarkBatchSplitResults],
+	storage_info: &[StorageInfo],
+	cmd: &BenchmarkCmd,
+) -> Result<(), std::io::Error> {
+	let json_file = cmd.json_file();
+	let csv_file = cmd.csv_file();
+	if json_file.is_none() && csv_file.is_none() {
+		return Ok(())
+	}
+
+	let analysis_choice = cmd.analysis_choice()?;
+	let all_results = utils::map_results(batches, storage_info, &analysis_choice)?;
+
+	if let Some(path) = json_file {
+		// Re-key by "pallet::instance" so the JSON schema doesn't depend on tuple ordering.
+		let by_pallet: HashMap<String, _> = all_results
+			.iter()
+			.map(|((pallet, instance), data)| (format!("{}::{}", pallet, instance), data))
+			.collect();
+		let file = fs::File::create(path)?;
+		serde_json::to_writer_pretty(file, &by_pallet)
+			.map_err(|e| utils::io_error(&e.to_string()))?;
+	}
+
+	if let Some(path) = csv_file {
+		let mut writer = csv::Writer::from_path(path).map_err(|e| utils::io_error(&e.to_string()))?;
+		for ((pallet, instance), results) in all_results.iter() {
+			for benchmark in results {
+				for row in csv_rows(pallet, instance, benchmark) {
+					writer.serialize(row).map_err(|e| utils::io_error(&e.to_string()))?;
+				}
+			}
+		}
+		writer.flush()?;
+	}
+
+	Ok(())
+}
+
+/// Build the CSV rows for a single benchmark: one per variable component, or a single `"-"`
+/// component row when it has none (a base-weight-only extrinsic), so its base weight/reads/
+/// writes/proof size aren't silently dropped from the CSV.
+fn csv_rows<'a>(
+	pallet: &'a str,
+	instance: &'a str,
+	benchmark: &'a utils::BenchmarkData,
+) -> Vec<CsvRow<'a>> {
+	let slope_for = |slopes: &[utils::ComponentSlope], name: &str| {
+		slopes.iter().find(|c| c.name == name).map(|c| c.slope).unwrap_or(0)
+	};
+
+	if benchmark.component_weight.is_empty() {
+		return vec![CsvRow {
+			pallet,
+			instance,
+			benchmark: &benchmark.name,
+			component: "-",
+			base_weight: benchmark.base_weight,
+			component_slope: 0,
+			component_error: 0,
+			base_reads: benchmark.base_reads,
+			component_reads: 0,
+			base_writes: benchmark.base_writes,
+			component_writes: 0,
+			base_proof_size: benchmark.base_proof_size,
+			component_proof_size: 0,
+		}]
+	}
+
+	benchmark
+		.component_weight
+		.iter()
+		.map(|component| CsvRow {
+			pallet,
+			instance,
+			benchmark: &benchmark.name,
+			component: &component.name,
+			base_weight: benchmark.base_weight,
+			component_slope: component.slope,
+			component_error: component.error,
+			base_reads: benchmark.base_reads,
+			component_reads: slope_for(&benchmark.component_reads, &component.name),
+			base_writes: benchmark.base_writes,
+			component_writes: slope_for(&benchmark.component_writes, &component.name),
+			base_proof_size: benchmark.base_proof_size,
+			component_proof_size: slope_for(&benchmark.component_proof_size, &component.name),
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::utils::{BenchmarkData, Component, ComponentSlope};
+
+	fn bench_with_components() -> BenchmarkData {
+		BenchmarkData {
+			name: "bench".to_string(),
+			components: vec![Component { name: "x".to_string(), is_used: true }],
+			base_weight: 10,
+			component_weight: vec![ComponentSlope { name: "x".to_string(), slope: 3, error: 1 }],
+			base_reads: 1,
+			component_reads: vec![ComponentSlope { name: "x".to_string(), slope: 2, error: 0 }],
+			base_writes: 1,
+			component_writes: vec![],
+			base_proof_size: 0,
+			component_proof_size: vec![],
+		}
+	}
+
+	fn bench_without_components() -> BenchmarkData {
+		BenchmarkData {
+			name: "noop".to_string(),
+			components: vec![],
+			base_weight: 42,
+			component_weight: vec![],
+			base_reads: 2,
+			component_reads: vec![],
+			base_writes: 3,
+			component_writes: vec![],
+			base_proof_size: 7,
+			component_proof_size: vec![],
+		}
+	}
+
+	#[test]
+	fn csv_rows_emits_one_row_per_component() {
+		let rows = csv_rows("pallet", "instance", &bench_with_components());
+		assert_eq!(rows.len(), 1);
+		assert_eq!(rows[0].component, "x");
+		assert_eq!(rows[0].component_slope, 3);
+		assert_eq!(rows[0].component_reads, 2);
+	}
+
+	#[test]
+	fn csv_rows_emits_a_placeholder_row_for_a_base_weight_only_benchmark() {
+		let rows = csv_rows("pallet", "instance", &bench_without_components());
+		assert_eq!(rows.len(), 1);
+		assert_eq!(rows[0].component, "-");
+		assert_eq!(rows[0].base_weight, 42);
+		assert_eq!(rows[0].base_reads, 2);
+		assert_eq!(rows[0].base_writes, 3);
+		assert_eq!(rows[0].base_proof_size, 7);
+	}
+}
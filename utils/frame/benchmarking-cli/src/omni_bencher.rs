@@ -0,0 +1,131 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020-2021 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Benchmark a raw runtime WASM blob without a running node or chain spec.
+//!
+//! The runtime's own `GenesisBuilder` API takes the place of a chain spec here: it is asked to
+//! hand back its default genesis config, which is then applied to build the state the benchmark
+//! extrinsics actually run against. The only assumption this makes about the runtime is
+//! `BlakeTwo256` hashing and the default set of host functions; see [`crate::command::PalletCmd`]
+//! for the `v1` path this supersedes.
+
+use std::path::PathBuf;
+
+use codec::{Decode, Encode};
+use sc_executor::{WasmExecutionMethod, WasmExecutor};
+use sp_core::traits::{CallContext, CodeExecutor, RuntimeCode};
+use sp_state_machine::BasicExternalities;
+use structopt::StructOpt;
+
+use frame_benchmarking::BenchmarkBatchSplitResults;
+
+use crate::command::{BenchmarkOutputParams, BenchmarkParams};
+
+/// Benchmark a runtime WASM blob directly.
+#[derive(Debug, StructOpt)]
+pub struct OmniBenchmarkCmd {
+	/// Path to a built runtime WASM blob, e.g. produced by `build.rs` or `srtool`.
+	#[structopt(long)]
+	pub runtime: PathBuf,
+
+	#[structopt(flatten)]
+	pub params: BenchmarkParams,
+
+	#[structopt(flatten)]
+	pub output: BenchmarkOutputParams,
+}
+
+impl OmniBenchmarkCmd {
+	/// Run the benchmarks against `self.runtime`, returning one split result per extrinsic.
+	///
+	/// Repeats the whole call `self.params.external_repeat` times, merging the samples of each
+	/// repeat into the matching batch, the same as the `v1` path does.
+	pub fn run(&self) -> sc_cli::Result<Vec<BenchmarkBatchSplitResults>> {
+		let repeats = (0..self.params.external_repeat.max(1))
+			.map(|_| self.dispatch_once())
+			.collect::<sc_cli::Result<Vec<_>>>()?;
+		Ok(crate::command::merge_batches(repeats))
+	}
+
+	/// Build genesis state entirely in memory from the runtime's own `GenesisBuilder` API, then
+	/// invoke `Benchmark_dispatch_benchmark` against it. No node, database or chain spec is ever
+	/// touched.
+	fn dispatch_once(&self) -> sc_cli::Result<Vec<BenchmarkBatchSplitResults>> {
+		let code = std::fs::read(&self.runtime)
+			.map_err(|e| sc_cli::Error::Input(format!("could not read runtime blob: {}", e)))?;
+		let code_hash = sp_core::blake2_256(&code).to_vec();
+		let runtime_code =
+			RuntimeCode { code_fetcher: &code[..], hash: code_hash, heap_pages: None };
+
+		let executor = WasmExecutor::<sp_io::SubstrateHostFunctions>::builder()
+			.with_execution_method(WasmExecutionMethod::default())
+			.build();
+
+		// Build genesis storage purely from the runtime's own `GenesisBuilder` API.
+		let mut ext = BasicExternalities::default();
+		let default_config: Vec<u8> = executor
+			.call(
+				&mut ext.ext(),
+				&runtime_code,
+				"GenesisBuilder_create_default_config",
+				&[],
+				CallContext::Offchain,
+			)
+			.0
+			.map_err(|e| sc_cli::Error::Application(e.into()))?;
+
+		executor
+			.call(
+				&mut ext.ext(),
+				&runtime_code,
+				"GenesisBuilder_build_config",
+				&default_config,
+				CallContext::Offchain,
+			)
+			.0
+			.map_err(|e| sc_cli::Error::Application(e.into()))?;
+
+		// Run the actual benchmarks against the freshly built genesis state.
+		let call_params = (
+			self.params.pallet.as_bytes().to_vec(),
+			self.params.extrinsic.as_bytes().to_vec(),
+			self.params.lowest_range_values.clone(),
+			self.params.highest_range_values.clone(),
+			self.params.steps.clone(),
+			self.params.repeat,
+			true, // whitelist is taken from the runtime's own defaults.
+		)
+			.encode();
+
+		let raw_results = executor
+			.call(
+				&mut ext.ext(),
+				&runtime_code,
+				"Benchmark_dispatch_benchmark",
+				&call_params,
+				CallContext::Offchain,
+			)
+			.0
+			.map_err(|e| sc_cli::Error::Application(e.into()))?;
+
+		let results: Result<Vec<BenchmarkBatchSplitResults>, String> =
+			Decode::decode(&mut &raw_results[..])
+				.map_err(|e| format!("failed to decode benchmark results: {:?}", e))?;
+
+		results.map_err(|e| sc_cli::Error::Application(e.into()))
+	}
+}
@@ -0,0 +1,199 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020-2021 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Benchmark per-key storage reads and writes against a node's database, to calibrate the
+//! storage weight constants rather than the extrinsic ones. `DatabaseSource` already abstracts
+//! over the backend for the node itself, so this just takes the same choice as a CLI flag.
+
+use std::{path::PathBuf, time::Instant};
+
+use sc_client_db::{DatabaseSettings, DatabaseSource, KeepBlocks, PruningMode, TransactionStorageMode};
+use sp_database::Database;
+use structopt::StructOpt;
+use tempfile::tempdir;
+
+use frame_benchmarking::{BenchmarkBatchSplitResults, BenchmarkParameter, BenchmarkResult};
+
+use crate::command::BenchmarkOutputParams;
+
+/// The database backend to calibrate storage weights against.
+#[derive(Debug, Clone, Copy)]
+pub enum DatabaseBackend {
+	RocksDb,
+	ParityDb,
+}
+
+impl std::str::FromStr for DatabaseBackend {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_lowercase().as_str() {
+			"rocksdb" => Ok(DatabaseBackend::RocksDb),
+			"paritydb" => Ok(DatabaseBackend::ParityDb),
+			other => Err(format!("unknown database backend: {}", other)),
+		}
+	}
+}
+
+/// Benchmark the per-key read/write latency of a node's database.
+#[derive(Debug, StructOpt)]
+pub struct StorageCmd {
+	/// Path to the database to benchmark. Its entire state trie is iterated.
+	#[structopt(long)]
+	pub database_path: PathBuf,
+
+	/// Which database backend `database_path` was created with: `rocksdb` or `paritydb`.
+	#[structopt(long, default_value = "rocksdb")]
+	pub database: DatabaseBackend,
+
+	/// How many times each key should be read to smooth out noise.
+	#[structopt(long, default_value = "1")]
+	pub repeat_reads: u32,
+
+	/// Also measure write and delete latency in a scratch copy of the database, instead of just
+	/// reads against the original.
+	#[structopt(long)]
+	pub include_writes: bool,
+
+	#[structopt(flatten)]
+	pub output: BenchmarkOutputParams,
+}
+
+/// One sampled read or write, keyed by the encoded value size it was measured against.
+struct Sample {
+	value_size: u32,
+	nanos: u128,
+}
+
+impl StorageCmd {
+	fn database_settings(&self, path: PathBuf) -> DatabaseSettings {
+		let source = match self.database {
+			DatabaseBackend::RocksDb => DatabaseSource::RocksDb { path, cache_size: 128 },
+			DatabaseBackend::ParityDb => DatabaseSource::ParityDb { path },
+		};
+		DatabaseSettings {
+			state_cache_size: 0,
+			state_cache_child_ratio: None,
+			state_pruning: PruningMode::ArchiveAll,
+			source,
+			keep_blocks: KeepBlocks::All,
+			transaction_storage: TransactionStorageMode::BlockBody,
+		}
+	}
+
+	/// Iterate the full state trie, measuring read (and optionally write/delete) latency as a
+	/// function of value size. The caller is expected to feed the result into
+	/// [`crate::writer::write_results`] / [`crate::html_writer::write_results`], exactly like the
+	/// extrinsic benchmarks, so storage weights land in the same generated files.
+	pub fn run(&self) -> sc_cli::Result<Vec<BenchmarkBatchSplitResults>> {
+		let db = sc_client_db::open_database::<sp_runtime::traits::BlakeTwo256>(
+			&self.database_settings(self.database_path.clone()),
+		)
+		.map_err(|e| sc_cli::Error::Application(e.into()))?;
+
+		let reads = self.sample_reads(db.as_ref());
+		let (writes, deletes) =
+			if self.include_writes { self.sample_writes(db.as_ref())? } else { (Vec::new(), Vec::new()) };
+
+		let mut batches = vec![to_batch("read", &reads)];
+		if !writes.is_empty() {
+			batches.push(to_batch("write", &writes));
+		}
+		if !deletes.is_empty() {
+			batches.push(to_batch("delete", &deletes));
+		}
+
+		Ok(batches)
+	}
+
+	/// Time a read of every key currently in the state column.
+	fn sample_reads(&self, db: &dyn Database<sp_database::error::DatabaseError>) -> Vec<Sample> {
+		let mut samples = Vec::new();
+		// Column 1 is `sc_client_db`'s state column (column 0 holds metadata).
+		for (key, value) in db.iter(sp_database::ColumnId::from(1u32)) {
+			for _ in 0..self.repeat_reads.max(1) {
+				let start = Instant::now();
+				let _ = db.get(sp_database::ColumnId::from(1u32), &key);
+				samples.push(Sample { value_size: value.len() as u32, nanos: start.elapsed().as_nanos() });
+			}
+		}
+		samples
+	}
+
+	/// Time writing and deleting the same keys in a scratch copy of the database, so `original`
+	/// (the already-open handle on `self.database_path`) is never mutated and never has to be
+	/// opened a second time.
+	fn sample_writes(
+		&self,
+		original: &dyn Database<sp_database::error::DatabaseError>,
+	) -> sc_cli::Result<(Vec<Sample>, Vec<Sample>)> {
+		let scratch_dir = tempdir().map_err(|e| sc_cli::Error::Input(e.to_string()))?;
+		let scratch = sc_client_db::open_database::<sp_runtime::traits::BlakeTwo256>(
+			&self.database_settings(scratch_dir.path().to_path_buf()),
+		)
+		.map_err(|e| sc_cli::Error::Application(e.into()))?;
+
+		let mut writes = Vec::new();
+		let mut deletes = Vec::new();
+		for (key, value) in original.iter(sp_database::ColumnId::from(1u32)) {
+			let mut transaction = sp_database::Transaction::new();
+			transaction.set(sp_database::ColumnId::from(1u32), &key, &value);
+			let start = Instant::now();
+			scratch
+				.commit(transaction)
+				.map_err(|e| sc_cli::Error::Application(e.into()))?;
+			writes.push(Sample { value_size: value.len() as u32, nanos: start.elapsed().as_nanos() });
+
+			let mut transaction = sp_database::Transaction::new();
+			transaction.remove(sp_database::ColumnId::from(1u32), &key);
+			let start = Instant::now();
+			scratch
+				.commit(transaction)
+				.map_err(|e| sc_cli::Error::Application(e.into()))?;
+			deletes.push(Sample { value_size: value.len() as u32, nanos: start.elapsed().as_nanos() });
+		}
+		Ok((writes, deletes))
+	}
+}
+
+/// Turn the raw `(value_size, nanos)` samples into a synthetic [`BenchmarkBatchSplitResults`],
+/// using the value size as the single variable component, so it can flow through the same
+/// `map_results` / `writer::write_results` pipeline as extrinsic benchmarks.
+fn to_batch(name: &str, samples: &[Sample]) -> BenchmarkBatchSplitResults {
+	let results: Vec<BenchmarkResult> = samples
+		.iter()
+		.map(|sample| BenchmarkResult {
+			components: vec![(BenchmarkParameter::v, sample.value_size)],
+			extrinsic_time: sample.nanos,
+			storage_root_time: sample.nanos,
+			reads: if name == "read" { 1 } else { 0 },
+			repeat_reads: 0,
+			writes: if name != "read" { 1 } else { 0 },
+			repeat_writes: 0,
+			proof_size: 0,
+			keys: vec![],
+		})
+		.collect();
+
+	BenchmarkBatchSplitResults {
+		pallet: b"storage".to_vec(),
+		instance: b"default".to_vec(),
+		benchmark: name.as_bytes().to_vec(),
+		time_results: results.clone(),
+		db_results: results,
+	}
+}
@@ -51,13 +51,13 @@ pub fn write_results(
 	cmd: &BenchmarkCmd,
 ) -> Result<(), std::io::Error> {
 	// Use custom template if provided.
-	let template: String = match &cmd.template {
+	let template: String = match cmd.template() {
 		Some(template_file) => fs::read_to_string(template_file)?,
 		None => TEMPLATE.to_string(),
 	};
 
 	// Use header if provided
-	let header_text = match &cmd.header {
+	let header_text = match cmd.header() {
 		Some(header_file) => {
 			let text = fs::read_to_string(header_file)?;
 			text